@@ -17,13 +17,13 @@ use actix_web::{
 };
 
 use casbin::prelude::{TryIntoAdapter, TryIntoModel};
-use casbin::{CachedEnforcer, CoreApi, Result as CasbinResult};
+use casbin::{CachedEnforcer, CoreApi, MgmtApi, RbacApi, Result as CasbinResult, Watcher};
 
 #[cfg(feature = "runtime-tokio")]
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, task::spawn};
 
 #[cfg(feature = "runtime-async-std")]
-use async_std::sync::RwLock;
+use async_std::{sync::RwLock, task::spawn};
 
 #[derive(Clone)]
 pub struct CasbinVals {
@@ -31,9 +31,30 @@ pub struct CasbinVals {
     pub domain: Option<String>,
 }
 
+/// The result of an enforcement attempt, handed to the decision observer.
+#[derive(Clone, Debug)]
+pub enum EnforceOutcome {
+    Allow,
+    Deny,
+    Error(String),
+}
+
+type Responder = Arc<dyn Fn(&ServiceRequest) -> HttpResponse + Send + Sync>;
+type Observer = Arc<dyn Fn(&ServiceRequest, &CasbinVals, &EnforceOutcome) + Send + Sync>;
+type AttributeExtractor = Arc<dyn Fn(&ServiceRequest) -> Vec<(String, String)> + Send + Sync>;
+type SkipPredicate = Arc<dyn Fn(&ServiceRequest) -> bool + Send + Sync>;
+
 #[derive(Clone)]
 pub struct CasbinService {
     enforcer: Arc<RwLock<CachedEnforcer>>,
+    read_only_enforce: bool,
+    unauthorized_responder: Option<Responder>,
+    forbidden_responder: Option<Responder>,
+    error_responder: Option<Responder>,
+    observer: Option<Observer>,
+    attribute_extractor: Option<AttributeExtractor>,
+    skip_paths: Vec<String>,
+    skip_predicate: Option<SkipPredicate>,
 }
 
 impl CasbinService {
@@ -41,6 +62,14 @@ impl CasbinService {
         let enforcer: CachedEnforcer = CachedEnforcer::new(m, a).await?;
         Ok(CasbinService {
             enforcer: Arc::new(RwLock::new(enforcer)),
+            read_only_enforce: false,
+            unauthorized_responder: None,
+            forbidden_responder: None,
+            error_responder: None,
+            observer: None,
+            attribute_extractor: None,
+            skip_paths: Vec::new(),
+            skip_predicate: None,
         })
     }
 
@@ -49,7 +78,217 @@ impl CasbinService {
     }
 
     pub fn set_enforcer(e: Arc<RwLock<CachedEnforcer>>) -> CasbinService {
-        CasbinService { enforcer: e }
+        CasbinService {
+            enforcer: e,
+            read_only_enforce: false,
+            unauthorized_responder: None,
+            forbidden_responder: None,
+            error_responder: None,
+            observer: None,
+            attribute_extractor: None,
+            skip_paths: Vec::new(),
+            skip_predicate: None,
+        }
+    }
+
+    // Enforce decisions behind a shared read lock instead of the per-request
+    // write lock taken by `CachedEnforcer::enforce_mut`. This lets N requests
+    // evaluate policy in parallel at the cost of the per-decision cache: results
+    // are recomputed on every call. Policy changes still require a write lock via
+    // the management API (e.g. `add_policy`).
+    pub fn read_only_enforce(mut self, read_only: bool) -> Self {
+        self.read_only_enforce = read_only;
+        self
+    }
+
+    // Response returned when the request carries no `CasbinVals` in its
+    // extensions, or carries one with an empty subject. Defaults to an empty
+    // `401 Unauthorized`.
+    pub fn unauthorized_responder<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.unauthorized_responder = Some(Arc::new(f));
+        self
+    }
+
+    // Response returned when enforcement denies the request. Defaults to an empty
+    // `403 Forbidden`.
+    pub fn forbidden_responder<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.forbidden_responder = Some(Arc::new(f));
+        self
+    }
+
+    // Response returned when the enforcer errors while evaluating the request.
+    // Defaults to an empty `502 Bad Gateway`.
+    pub fn error_responder<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.error_responder = Some(Arc::new(f));
+        self
+    }
+
+    // Callback fired on every allow/deny/error decision, receiving the request,
+    // its `CasbinVals` and the outcome. Use it to emit a structured grant/deny
+    // trace for auditing; the middleware itself logs nothing.
+    pub fn observe<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest, &CasbinVals, &EnforceOutcome) + Send + Sync + 'static,
+    {
+        self.observer = Some(Arc::new(f));
+        self
+    }
+
+    // Supply extra matcher arguments pulled from the request (client IP, headers,
+    // query params, resource owner, ...). The extracted values are appended, in
+    // order, after the `subject, [domain,] path, action` tuple passed to the
+    // enforcer, so ABAC models can reference them as additional request fields.
+    pub fn attribute_extractor<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> Vec<(String, String)> + Send + Sync + 'static,
+    {
+        self.attribute_extractor = Some(Arc::new(f));
+        self
+    }
+
+    // Exempt request paths from enforcement by glob pattern (`*` matches any run
+    // of characters, `?` a single one), e.g. `/health`, `/static/*`. Matching
+    // requests are passed straight to the inner service without requiring
+    // `CasbinVals`, which is how public routes are left open under an app-wide
+    // authorization layer.
+    pub fn skip_paths<I, P>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.skip_paths = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // Skip enforcement whenever the predicate returns `true`. Evaluated alongside
+    // `skip_paths`, before the `CasbinVals` lookup.
+    pub fn skip_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + Send + Sync + 'static,
+    {
+        self.skip_predicate = Some(Arc::new(f));
+        self
+    }
+
+    // Build a service whose enforcer reloads policy from the adapter whenever the
+    // supplied watcher fires an external change notification. This keeps decisions
+    // consistent across a horizontally scaled fleet, where each `CachedEnforcer`
+    // otherwise only sees the policy it loaded at construction.
+    pub async fn with_auto_reload<M: TryIntoModel, A: TryIntoAdapter>(
+        m: M,
+        a: A,
+        mut watcher: Box<dyn Watcher>,
+    ) -> CasbinResult<Self> {
+        let service = CasbinService::new(m, a).await?;
+        let enforcer = service.enforcer.clone();
+        watcher.set_update_callback(Box::new(move || {
+            let enforcer = enforcer.clone();
+            spawn(async move {
+                let mut lock = enforcer.write().await;
+                let _ = lock.load_policy().await;
+            });
+        }));
+        service.enforcer.write().await.set_watcher(watcher);
+        Ok(service)
+    }
+
+    // Register a watcher on the enforcer after construction. The watcher's update
+    // callback is left untouched, so the caller owns the reload behaviour; use
+    // `with_auto_reload` to wire it to `reload_policy` automatically.
+    pub async fn set_watcher(&self, w: Box<dyn Watcher>) {
+        let mut lock = self.enforcer.write().await;
+        lock.set_watcher(w);
+    }
+
+    // Reload policy from the adapter under the write lock, clearing the decision
+    // cache. Exposed for callers that react to change notifications themselves.
+    pub async fn reload_policy(&self) -> CasbinResult<()> {
+        let mut lock = self.enforcer.write().await;
+        lock.load_policy().await
+    }
+
+    // Runtime policy and role management. Each method takes the write lock
+    // internally and delegates to the enforcer; mutating through `CachedEnforcer`
+    // invalidates the decision cache, so callers don't have to touch the lock.
+    pub async fn add_policy(&self, params: Vec<String>) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.add_policy(params).await
+    }
+
+    pub async fn add_policies(&self, params: Vec<Vec<String>>) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.add_policies(params).await
+    }
+
+    pub async fn remove_policy(&self, params: Vec<String>) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.remove_policy(params).await
+    }
+
+    pub async fn remove_filtered_policy(
+        &self,
+        field_index: usize,
+        field_values: Vec<String>,
+    ) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.remove_filtered_policy(field_index, field_values).await
+    }
+
+    pub async fn add_role_for_user(
+        &self,
+        user: &str,
+        role: &str,
+        domain: Option<&str>,
+    ) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.add_role_for_user(user, role, domain).await
+    }
+
+    pub async fn add_roles_for_user(
+        &self,
+        user: &str,
+        roles: Vec<String>,
+        domain: Option<&str>,
+    ) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.add_roles_for_user(user, roles, domain).await
+    }
+
+    pub async fn delete_role_for_user(
+        &self,
+        user: &str,
+        role: &str,
+        domain: Option<&str>,
+    ) -> CasbinResult<bool> {
+        let mut lock = self.enforcer.write().await;
+        lock.delete_role_for_user(user, role, domain).await
+    }
+
+    pub async fn get_implicit_roles_for_user(
+        &self,
+        name: &str,
+        domain: Option<&str>,
+    ) -> Vec<String> {
+        let mut lock = self.enforcer.write().await;
+        lock.get_implicit_roles_for_user(name, domain)
+    }
+
+    pub async fn get_implicit_permissions_for_user(
+        &self,
+        name: &str,
+        domain: Option<&str>,
+    ) -> Vec<Vec<String>> {
+        let mut lock = self.enforcer.write().await;
+        lock.get_implicit_permissions_for_user(name, domain)
     }
 }
 
@@ -67,6 +306,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(CasbinMiddleware {
             enforcer: self.enforcer.clone(),
+            read_only_enforce: self.read_only_enforce,
+            unauthorized_responder: self.unauthorized_responder.clone(),
+            forbidden_responder: self.forbidden_responder.clone(),
+            error_responder: self.error_responder.clone(),
+            observer: self.observer.clone(),
+            attribute_extractor: self.attribute_extractor.clone(),
+            skip_paths: self.skip_paths.clone(),
+            skip_predicate: self.skip_predicate.clone(),
             service: Rc::new(RefCell::new(service)),
         })
     }
@@ -89,6 +336,14 @@ impl DerefMut for CasbinService {
 pub struct CasbinMiddleware<S> {
     service: Rc<RefCell<S>>,
     enforcer: Arc<RwLock<CachedEnforcer>>,
+    read_only_enforce: bool,
+    unauthorized_responder: Option<Responder>,
+    forbidden_responder: Option<Responder>,
+    error_responder: Option<Responder>,
+    observer: Option<Observer>,
+    attribute_extractor: Option<AttributeExtractor>,
+    skip_paths: Vec<String>,
+    skip_predicate: Option<SkipPredicate>,
 }
 
 impl<S, B> Service<ServiceRequest> for CasbinMiddleware<S>
@@ -106,71 +361,139 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let cloned_enforcer = self.enforcer.clone();
+        let read_only_enforce = self.read_only_enforce;
+        let unauthorized_responder = self.unauthorized_responder.clone();
+        let forbidden_responder = self.forbidden_responder.clone();
+        let error_responder = self.error_responder.clone();
+        let observer = self.observer.clone();
+        let attribute_extractor = self.attribute_extractor.clone();
+        let skip_paths = self.skip_paths.clone();
+        let skip_predicate = self.skip_predicate.clone();
         let srv = self.service.clone();
 
         async move {
+            // Public routes bypass enforcement entirely, before the `CasbinVals`
+            // lookup that would otherwise reject them with `401`.
+            let skip = skip_paths.iter().any(|p| glob_match(p, req.path()))
+                || skip_predicate.as_ref().is_some_and(|f| f(&req));
+            if skip {
+                return srv.call(req).await.map(|res| res.map_into_left_body());
+            }
+
             let path = req.path().to_string();
             let action = req.method().as_str().to_string();
             let option_vals = req.extensions().get::<CasbinVals>().map(|x| x.to_owned());
             let vals = match option_vals {
                 Some(value) => value,
-                None => {
-                    return Ok(req.into_response(
-                        HttpResponse::Unauthorized().finish().map_into_right_body(),
-                    ))
+                None => return Ok(unauthorized(&unauthorized_responder, req)),
+            };
+
+            if vals.subject.is_empty() {
+                return Ok(unauthorized(&unauthorized_responder, req));
+            }
+
+            let mut args = vec![vals.subject.clone()];
+            if let Some(domain) = vals.domain.clone() {
+                args.push(domain);
+            }
+            args.push(path);
+            args.push(action);
+            if let Some(extract) = &attribute_extractor {
+                for (_key, value) in extract(&req) {
+                    args.push(value);
                 }
+            }
+
+            // In read-only mode policy is evaluated behind a shared read guard so
+            // concurrent requests don't serialize on the writer; otherwise we take
+            // the write lock and go through the cached `enforce_mut` path.
+            let outcome = if read_only_enforce {
+                cloned_enforcer.read().await.enforce(args)
+            } else {
+                cloned_enforcer.write().await.enforce_mut(args)
             };
-            let subject = vals.subject.clone();
-
-            if !vals.subject.is_empty() {
-                if let Some(domain) = vals.domain {
-                    let mut lock = cloned_enforcer.write().await;
-                    eprintln!("{subject:?}{domain:?}{path:?}{action:?}");
-                    match lock.enforce_mut(vec![subject, domain, path, action]) {
-                        Ok(true) => {
-                            drop(lock);
-                            srv.call(req).await.map(|res| res.map_into_left_body())
-                        }
-                        Ok(false) => {
-                            drop(lock);
-                            Ok(req.into_response(
-                                HttpResponse::Forbidden().finish().map_into_right_body(),
-                            ))
-                        }
-                        Err(err) => {
-                            eprintln!("140 {err:?}");
-                            drop(lock);
-                            Ok(req.into_response(
-                                HttpResponse::BadGateway().finish().map_into_right_body(),
-                            ))
-                        }
+
+            match outcome {
+                Ok(true) => {
+                    if let Some(observe) = &observer {
+                        observe(&req, &vals, &EnforceOutcome::Allow);
                     }
-                } else {
-                    let mut lock = cloned_enforcer.write().await;
-                    match lock.enforce_mut(vec![subject, path, action]) {
-                        Ok(true) => {
-                            drop(lock);
-                            srv.call(req).await.map(|res| res.map_into_left_body())
-                        }
-                        Ok(false) => {
-                            drop(lock);
-                            Ok(req.into_response(
-                                HttpResponse::Forbidden().finish().map_into_right_body(),
-                            ))
-                        }
-                        Err(err) => {
-                            eprintln!("161 {err:?}");
-                            drop(lock);
-                            Ok(req.into_response(
-                                HttpResponse::BadGateway().finish().map_into_right_body(),
-                            ))
-                        }
+                    srv.call(req).await.map(|res| res.map_into_left_body())
+                }
+                Ok(false) => {
+                    if let Some(observe) = &observer {
+                        observe(&req, &vals, &EnforceOutcome::Deny);
                     }
+                    Ok(respond(
+                        &forbidden_responder,
+                        req,
+                        HttpResponse::Forbidden().finish(),
+                    ))
+                }
+                Err(err) => {
+                    if let Some(observe) = &observer {
+                        observe(&req, &vals, &EnforceOutcome::Error(err.to_string()));
+                    }
+                    Ok(respond(
+                        &error_responder,
+                        req,
+                        HttpResponse::BadGateway().finish(),
+                    ))
                 }
-            } else {
-                Ok(req.into_response(HttpResponse::Unauthorized().finish().map_into_right_body()))
             }
         }
         .boxed_local()
     }
 }
+
+// Turn a configured responder (or the built-in default) into a service response
+// carrying the right-hand body of the middleware's `EitherBody`.
+fn respond<B>(
+    responder: &Option<Responder>,
+    req: ServiceRequest,
+    default: HttpResponse,
+) -> ServiceResponse<EitherBody<B>> {
+    let response = match responder {
+        Some(f) => f(&req),
+        None => default,
+    };
+    req.into_response(response.map_into_right_body())
+}
+
+fn unauthorized<B>(
+    responder: &Option<Responder>,
+    req: ServiceRequest,
+) -> ServiceResponse<EitherBody<B>> {
+    respond(responder, req, HttpResponse::Unauthorized().finish())
+}
+
+// Match `path` against a glob where `*` consumes any run of characters and `?`
+// exactly one. Used for the exempt-path whitelist; kept dependency-free so the
+// middleware doesn't pull in a glob crate just for prefix/suffix patterns.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let txt: Vec<char> = path.chars().collect();
+    // Greedy match with backtracking on `*`.
+    let (mut p, mut t) = (0, 0);
+    let (mut star, mut mark) = (None, 0);
+    while t < txt.len() {
+        if p < pat.len() && (pat[p] == '?' || pat[p] == txt[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pat.len() && pat[p] == '*' {
+            star = Some(p);
+            mark = t;
+            p += 1;
+        } else if let Some(sp) = star {
+            p = sp + 1;
+            mark += 1;
+            t = mark;
+        } else {
+            return false;
+        }
+    }
+    while p < pat.len() && pat[p] == '*' {
+        p += 1;
+    }
+    p == pat.len()
+}